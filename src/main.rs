@@ -1,26 +1,26 @@
 extern crate nalgebra as na;
-use std::thread::Thread;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
+use image::{Rgb, RgbImage};
+use indicatif::ProgressBar;
 use na::Vector3;
 use rand::prelude::*;
 
-trait Color {
-    fn to_string(&self, samples_per_pixel: i32) -> String;
-}
-
-fn color_to_string(color: &Vector3<f32>, samples_per_pixel: i32) -> String {
-    let (mut r, mut g, mut b) = (color.x, color.y, color.z);
-
+fn color_to_rgb(color: &Vector3<f32>, samples_per_pixel: i32) -> Rgb<u8> {
     let scale = 1.0 / (samples_per_pixel as f32);
-    r *= scale;
-    g *= scale;
-    b *= scale;
 
-    let ir = (256.0 * r.clamp(0.0, 0.999)) as i32;
-    let ig = (256.0 * g.clamp(0.0, 0.999)) as i32;
-    let ib = (256.0 * b.clamp(0.0, 0.999)) as i32;
+    // Gamma-2 correct before mapping to the [0, 255] byte range.
+    let r = (color.x * scale).sqrt();
+    let g = (color.y * scale).sqrt();
+    let b = (color.z * scale).sqrt();
 
-    format!("{} {} {}\n", ir, ig, ib)
+    Rgb([
+        (256.0 * r.clamp(0.0, 0.999)) as u8,
+        (256.0 * g.clamp(0.0, 0.999)) as u8,
+        (256.0 * b.clamp(0.0, 0.999)) as u8,
+    ])
 }
 
 pub struct Camera {
@@ -28,34 +28,63 @@ pub struct Camera {
     horizontal: Vector3<f32>,
     vertical: Vector3<f32>,
     lower_left_corner: Vector3<f32>,
+    u: Vector3<f32>,
+    v: Vector3<f32>,
+    lens_radius: f32,
+    time0: f32,
+    time1: f32,
 }
 
 impl Camera {
-    fn new(aspect_ratio: f32) -> Self {
-        let viewport_height = 2.0;
+    fn new(
+        look_from: Vector3<f32>,
+        look_at: Vector3<f32>,
+        vup: Vector3<f32>,
+        vfov_degrees: f32,
+        aspect_ratio: f32,
+        aperture: f32,
+        focus_dist: f32,
+        time0: f32,
+        time1: f32,
+    ) -> Self {
+        let theta = vfov_degrees.to_radians();
+        let h = (theta / 2.0).tan();
+        let viewport_height = 2.0 * h;
         let viewport_width = aspect_ratio * viewport_height;
-        let focal_length = 1.0;
 
-        let origin = Vector3::new(0.0, 0.0, 0.0);
-        let horizontal = Vector3::new(viewport_width, 0.0, 0.0);
-        let vertical = Vector3::new(0.0, viewport_height, 0.0);
+        let w = (look_from - look_at).normalize();
+        let u = vup.cross(&w).normalize();
+        let v = w.cross(&u);
 
-        let lower_left_corner =
-            origin - horizontal / 2.0 - vertical / 2.0 - Vector3::new(0.0, 0.0, focal_length);
+        let origin = look_from;
+        let horizontal = focus_dist * viewport_width * u;
+        let vertical = focus_dist * viewport_height * v;
+
+        let lower_left_corner = origin - horizontal / 2.0 - vertical / 2.0 - focus_dist * w;
 
         Camera {
             origin,
             horizontal,
             vertical,
             lower_left_corner,
+            u,
+            v,
+            lens_radius: aperture / 2.0,
+            time0,
+            time1,
         }
     }
 
-    fn get_ray(&self, u: f32, v: f32) -> Ray {
+    fn get_ray(&self, s: f32, t: f32, rng: &mut ThreadRng) -> Ray {
+        let rd = self.lens_radius * random_in_unit_disk(rng);
+        let offset = self.u * rd.x + self.v * rd.y;
+
         Ray {
-            origin: self.origin,
-            direction: self.lower_left_corner + u * self.horizontal + v * self.vertical
-                - self.origin,
+            origin: self.origin + offset,
+            direction: self.lower_left_corner + s * self.horizontal + t * self.vertical
+                - self.origin
+                - offset,
+            time: rng.gen_range(self.time0..self.time1),
         }
     }
 }
@@ -63,6 +92,7 @@ impl Camera {
 pub struct Ray {
     origin: Vector3<f32>,
     direction: Vector3<f32>,
+    time: f32,
 }
 
 fn random_in_unit_sphere(rng: &mut ThreadRng) -> Vector3<f32> {
@@ -76,34 +106,196 @@ fn random_in_unit_sphere(rng: &mut ThreadRng) -> Vector3<f32> {
     }
 }
 
+fn random_unit_vector(rng: &mut ThreadRng) -> Vector3<f32> {
+    random_in_unit_sphere(rng).normalize()
+}
+
+fn random_in_unit_disk(rng: &mut ThreadRng) -> Vector3<f32> {
+    loop {
+        let v = Vector3::new(2.0 * rng.gen::<f32>() - 1.0, 2.0 * rng.gen::<f32>() - 1.0, 0.0);
+
+        if v.norm_squared() < 1.0 {
+            return v;
+        }
+    }
+}
+
+fn reflect(v: &Vector3<f32>, n: &Vector3<f32>) -> Vector3<f32> {
+    v - 2.0 * v.dot(n) * n
+}
+
+fn refract(uv: &Vector3<f32>, n: &Vector3<f32>, etai_over_etat: f32) -> Vector3<f32> {
+    let cos_theta = (-uv).dot(n).min(1.0);
+    let r_out_perp = etai_over_etat * (uv + cos_theta * n);
+    let r_out_parallel = -(1.0 - r_out_perp.norm_squared()).abs().sqrt() * n;
+    r_out_perp + r_out_parallel
+}
+
+fn schlick(cosine: f32, ior: f32) -> f32 {
+    let r0 = (1.0 - ior) / (1.0 + ior);
+    let r0 = r0 * r0;
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+pub trait Material: Send + Sync {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit: &HitRecord,
+        rng: &mut ThreadRng,
+    ) -> Option<(Ray, Vector3<f32>)>;
+
+    fn emitted(&self) -> Vector3<f32> {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+pub struct Lambertian {
+    albedo: Vector3<f32>,
+}
+
+impl Material for Lambertian {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit: &HitRecord,
+        rng: &mut ThreadRng,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let mut scatter_direction = hit.normal + random_unit_vector(rng);
+
+        // Catch degenerate scatter direction.
+        if scatter_direction.norm_squared() < 1.0e-16 {
+            scatter_direction = hit.normal;
+        }
+
+        let scattered = Ray {
+            origin: hit.p,
+            direction: scatter_direction,
+            time: ray_in.time,
+        };
+
+        Some((scattered, self.albedo))
+    }
+}
+
+pub struct Metal {
+    albedo: Vector3<f32>,
+    fuzz: f32,
+}
+
+impl Material for Metal {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit: &HitRecord,
+        rng: &mut ThreadRng,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let reflected = reflect(&ray_in.direction.normalize(), &hit.normal);
+        let scattered = Ray {
+            origin: hit.p,
+            direction: reflected + self.fuzz * random_in_unit_sphere(rng),
+            time: ray_in.time,
+        };
+
+        if scattered.direction.dot(&hit.normal) > 0.0 {
+            Some((scattered, self.albedo))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Dielectric {
+    ior: f32,
+}
+
+impl Material for Dielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit: &HitRecord,
+        rng: &mut ThreadRng,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        let attenuation = Vector3::new(1.0, 1.0, 1.0);
+        let refraction_ratio = if hit.front_face {
+            1.0 / self.ior
+        } else {
+            self.ior
+        };
+
+        let unit_direction = ray_in.direction.normalize();
+        let cos_theta = (-unit_direction).dot(&hit.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let cannot_refract = refraction_ratio * sin_theta > 1.0;
+        let direction =
+            if cannot_refract || schlick(cos_theta, refraction_ratio) > rng.gen::<f32>() {
+                reflect(&unit_direction, &hit.normal)
+            } else {
+                refract(&unit_direction, &hit.normal, refraction_ratio)
+            };
+
+        Some((
+            Ray {
+                origin: hit.p,
+                direction,
+                time: ray_in.time,
+            },
+            attenuation,
+        ))
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Vector3<f32>,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _hit: &HitRecord,
+        _rng: &mut ThreadRng,
+    ) -> Option<(Ray, Vector3<f32>)> {
+        None
+    }
+
+    fn emitted(&self) -> Vector3<f32> {
+        self.emit
+    }
+}
+
 impl Ray {
     fn at(&self, t: f32) -> Vector3<f32> {
         self.origin + t * self.direction
     }
 
-    fn color(&self, world: &dyn Hittable, depth: i32, rng: &mut ThreadRng) -> Vector3<f32> {
+    fn color(
+        &self,
+        world: &dyn Hittable,
+        background: &Vector3<f32>,
+        depth: i32,
+        rng: &mut ThreadRng,
+    ) -> Vector3<f32> {
         // If we've exceeded the ray bounce limit, no more light is gathered.
         if depth <= 0 {
             return Vector3::new(0.0, 0.0, 0.0);
         }
 
-        if let Some(hit) = world.hit(self, 0.0, 1000.0) {
-            let target = hit.p + hit.normal + random_in_unit_sphere(rng);
-            return 0.5
-                * Ray::color(
-                    &Ray {
-                        origin: hit.p,
-                        direction: target - hit.p,
-                    },
-                    world,
-                    depth - 1,
-                    rng,
-                );
-        }
+        let hit = match world.hit(self, 0.0, 1000.0) {
+            Some(hit) => hit,
+            None => return *background,
+        };
+
+        let emitted = hit.material.emitted();
 
-        let normalized_direction = self.direction.normalize();
-        let t = 0.5 * (normalized_direction.y + 1.0);
-        (1.0 - t) * Vector3::new(1.0, 1.0, 1.0) + t * Vector3::new(0.5, 0.7, 1.0)
+        let scattered = match hit.material.scatter(self, &hit, rng) {
+            Some(scattered) => scattered,
+            None => return emitted,
+        };
+
+        let (scattered, attenuation) = scattered;
+        emitted + attenuation.component_mul(&Ray::color(&scattered, world, background, depth - 1, rng))
     }
 }
 
@@ -112,10 +304,17 @@ pub struct HitRecord {
     normal: Vector3<f32>,
     t: f32,
     front_face: bool,
+    material: Arc<dyn Material>,
 }
 
 impl HitRecord {
-    fn from(ray: &Ray, p: Vector3<f32>, t: f32, outward_normal: Vector3<f32>) -> Self {
+    fn from(
+        ray: &Ray,
+        p: Vector3<f32>,
+        t: f32,
+        outward_normal: Vector3<f32>,
+        material: Arc<dyn Material>,
+    ) -> Self {
         let front_face = ray.direction.dot(&outward_normal) < 0.0;
 
         Self {
@@ -127,17 +326,67 @@ impl HitRecord {
             },
             t,
             front_face,
+            material,
         }
     }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb>;
+}
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    minimum: Vector3<f32>,
+    maximum: Vector3<f32>,
+}
+
+impl Aabb {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction[axis];
+            let mut t0 = (self.minimum[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.maximum[axis] - ray.origin[axis]) * inv_d;
+
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+fn surrounding_box(box0: Aabb, box1: Aabb) -> Aabb {
+    let minimum = Vector3::new(
+        box0.minimum.x.min(box1.minimum.x),
+        box0.minimum.y.min(box1.minimum.y),
+        box0.minimum.z.min(box1.minimum.z),
+    );
+    let maximum = Vector3::new(
+        box0.maximum.x.max(box1.maximum.x),
+        box0.maximum.y.max(box1.maximum.y),
+        box0.maximum.z.max(box1.maximum.z),
+    );
+
+    Aabb { minimum, maximum }
 }
 
 pub struct Sphere {
     center: Vector3<f32>,
     radius: f32,
+    material: Arc<dyn Material>,
 }
 
 impl Hittable for Sphere {
@@ -164,7 +413,88 @@ impl Hittable for Sphere {
         let t = root;
         let p = ray.at(root);
 
-        Some(HitRecord::from(ray, p, t, (p - self.center) / self.radius))
+        Some(HitRecord::from(
+            ray,
+            p,
+            t,
+            (p - self.center) / self.radius,
+            Arc::clone(&self.material),
+        ))
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        let radius = Vector3::new(self.radius, self.radius, self.radius);
+
+        Some(Aabb {
+            minimum: self.center - radius,
+            maximum: self.center + radius,
+        })
+    }
+}
+
+pub struct MovingSphere {
+    center0: Vector3<f32>,
+    center1: Vector3<f32>,
+    time0: f32,
+    time1: f32,
+    radius: f32,
+    material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    fn center(&self, time: f32) -> Vector3<f32> {
+        self.center0
+            + ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let center = self.center(ray.time);
+        let oc = ray.origin - center;
+        let a = ray.direction.norm_squared();
+        let half_b = oc.dot(&ray.direction);
+        let c = oc.norm_squared() - self.radius * self.radius;
+
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        // Find the nearest root that lies in the acceptable range.
+        let mut root = (-half_b - discriminant.sqrt()) / a;
+        if root < t_min || t_max < root {
+            root = -(half_b + discriminant.sqrt()) / a;
+            if root < t_min || t_max < root {
+                return None;
+            }
+        }
+
+        let t = root;
+        let p = ray.at(root);
+
+        Some(HitRecord::from(
+            ray,
+            p,
+            t,
+            (p - center) / self.radius,
+            Arc::clone(&self.material),
+        ))
+    }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        let radius = Vector3::new(self.radius, self.radius, self.radius);
+
+        let box0 = Aabb {
+            minimum: self.center(time0) - radius,
+            maximum: self.center(time0) + radius,
+        };
+        let box1 = Aabb {
+            minimum: self.center(time1) - radius,
+            maximum: self.center(time1) + radius,
+        };
+
+        Some(surrounding_box(box0, box1))
     }
 }
 
@@ -186,6 +516,100 @@ impl Hittable for Objects {
 
         closest_hit
     }
+
+    fn bounding_box(&self, time0: f32, time1: f32) -> Option<Aabb> {
+        if self.objects.is_empty() {
+            return None;
+        }
+
+        let mut output_box: Option<Aabb> = None;
+
+        for object in &self.objects {
+            let bbox = object.bounding_box(time0, time1)?;
+            output_box = Some(match output_box {
+                Some(existing) => surrounding_box(existing, bbox),
+                None => bbox,
+            });
+        }
+
+        output_box
+    }
+}
+
+pub struct BvhNode {
+    left: Box<dyn Hittable>,
+    right: Option<Box<dyn Hittable>>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    fn new(mut objects: Vec<Box<dyn Hittable>>, time0: f32, time1: f32) -> Self {
+        let axis = rand::thread_rng().gen_range(0..3);
+        objects.sort_by(|a, b| {
+            let box_a = a
+                .bounding_box(time0, time1)
+                .expect("no bounding box in BvhNode constructor");
+            let box_b = b
+                .bounding_box(time0, time1)
+                .expect("no bounding box in BvhNode constructor");
+
+            box_a.minimum[axis]
+                .partial_cmp(&box_b.minimum[axis])
+                .unwrap()
+        });
+
+        let (left, right): (Box<dyn Hittable>, Option<Box<dyn Hittable>>) = match objects.len() {
+            1 => (objects.pop().unwrap(), None),
+            2 => {
+                let right = objects.pop().unwrap();
+                let left = objects.pop().unwrap();
+                (left, Some(right))
+            }
+            len => {
+                let rest = objects.split_off(len / 2);
+                (
+                    Box::new(BvhNode::new(objects, time0, time1)),
+                    Some(Box::new(BvhNode::new(rest, time0, time1)) as Box<dyn Hittable>),
+                )
+            }
+        };
+
+        let left_box = left
+            .bounding_box(time0, time1)
+            .expect("no bounding box in BvhNode constructor");
+        let bbox = match &right {
+            Some(right) => surrounding_box(
+                left_box,
+                right
+                    .bounding_box(time0, time1)
+                    .expect("no bounding box in BvhNode constructor"),
+            ),
+            None => left_box,
+        };
+
+        BvhNode { left, right, bbox }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, t_min, t_max) {
+            return None;
+        }
+
+        let hit_left = self.left.hit(ray, t_min, t_max);
+        let t_max = hit_left.as_ref().map_or(t_max, |hit| hit.t);
+        let hit_right = self
+            .right
+            .as_ref()
+            .and_then(|right| right.hit(ray, t_min, t_max));
+
+        hit_right.or(hit_left)
+    }
+
+    fn bounding_box(&self, _time0: f32, _time1: f32) -> Option<Aabb> {
+        Some(self.bbox)
+    }
 }
 
 fn main() {
@@ -195,46 +619,145 @@ fn main() {
     const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f32 / ASPECT_RATIO) as i32;
     const SAMPLES_PER_PIXEL: i32 = 100;
     const MAX_DEPTH: i32 = 50;
+    let background = Vector3::new(0.5, 0.7, 1.0);
 
     // World
+    let material_ground: Arc<dyn Material> = Arc::new(Lambertian {
+        albedo: Vector3::new(0.8, 0.8, 0.0),
+    });
+    let material_center: Arc<dyn Material> = Arc::new(Lambertian {
+        albedo: Vector3::new(0.7, 0.3, 0.3),
+    });
+    let material_left: Arc<dyn Material> = Arc::new(Metal {
+        albedo: Vector3::new(0.8, 0.8, 0.8),
+        fuzz: 0.3,
+    });
+    let material_right: Arc<dyn Material> = Arc::new(Dielectric { ior: 1.5 });
+    let material_light: Arc<dyn Material> = Arc::new(DiffuseLight {
+        emit: Vector3::new(4.0, 4.0, 4.0),
+    });
+
     let world = Objects {
         objects: vec![
             Box::new(Sphere {
-                center: Vector3::new(0.0, 0.0, -1.0),
+                center: Vector3::new(0.0, -100.5, -1.0),
+                radius: 100.0,
+                material: Arc::clone(&material_ground),
+            }),
+            Box::new(MovingSphere {
+                center0: Vector3::new(0.0, 0.0, -1.0),
+                center1: Vector3::new(0.0, 0.3, -1.0),
+                time0: 0.0,
+                time1: 1.0,
                 radius: 0.5,
+                material: Arc::clone(&material_center),
             }),
             Box::new(Sphere {
-                center: Vector3::new(0.0, -100.5, -1.0),
-                radius: 100.0,
+                center: Vector3::new(-1.0, 0.0, -1.0),
+                radius: 0.5,
+                material: Arc::clone(&material_left),
+            }),
+            Box::new(Sphere {
+                center: Vector3::new(1.0, 0.0, -1.0),
+                radius: 0.5,
+                material: Arc::clone(&material_right),
+            }),
+            Box::new(Sphere {
+                center: Vector3::new(0.0, 1.5, -1.0),
+                radius: 0.3,
+                material: Arc::clone(&material_light),
             }),
         ],
     };
 
     // Camera
-    let camera = Camera::new(ASPECT_RATIO);
+    let look_from = Vector3::new(3.0, 3.0, 2.0);
+    let look_at = Vector3::new(0.0, 0.0, -1.0);
+    let vup = Vector3::new(0.0, 1.0, 0.0);
+    let dist_to_focus = (look_from - look_at).norm();
+    let aperture = 2.0;
+
+    let camera = Camera::new(
+        look_from,
+        look_at,
+        vup,
+        20.0,
+        ASPECT_RATIO,
+        aperture,
+        dist_to_focus,
+        0.0,
+        1.0,
+    );
 
     // Render
-    let mut content = format!("P3\n {} {}\n255\n", IMAGE_WIDTH, IMAGE_HEIGHT);
+    let world = Arc::new(BvhNode::new(world.objects, 0.0, 1.0));
+    let camera = Arc::new(camera);
+
+    let num_workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+
+    let row_queue = Arc::new(Mutex::new((0..IMAGE_HEIGHT).rev().collect::<Vec<i32>>()));
+    let (tx, rx) = mpsc::channel();
+
+    for _ in 0..num_workers {
+        let row_queue = Arc::clone(&row_queue);
+        let world = Arc::clone(&world);
+        let camera = Arc::clone(&camera);
+        let tx = tx.clone();
 
-    let mut rng = rand::thread_rng();
+        thread::spawn(move || {
+            let mut rng = rand::thread_rng();
 
-    for j in (0..IMAGE_HEIGHT).rev() {
-        eprintln!("Scanlines remaining: {}", j);
+            while let Some(j) = row_queue.lock().unwrap().pop() {
+                let mut row = Vec::with_capacity(IMAGE_WIDTH as usize);
 
-        for i in 0..IMAGE_WIDTH {
-            let mut pixel_color = Vector3::new(0.0, 0.0, 0.0);
+                for i in 0..IMAGE_WIDTH {
+                    let mut pixel_color = Vector3::new(0.0, 0.0, 0.0);
 
-            for _ in 0..SAMPLES_PER_PIXEL {
-                let u = (i as f32 + rng.gen::<f32>()) / (IMAGE_WIDTH - 1) as f32;
-                let v = (j as f32 + rng.gen::<f32>()) / (IMAGE_HEIGHT - 1) as f32;
-                let ray = camera.get_ray(u, v);
-                pixel_color += ray.color(&world, MAX_DEPTH, &mut rng);
+                    for _ in 0..SAMPLES_PER_PIXEL {
+                        let u = (i as f32 + rng.gen::<f32>()) / (IMAGE_WIDTH - 1) as f32;
+                        let v = (j as f32 + rng.gen::<f32>()) / (IMAGE_HEIGHT - 1) as f32;
+                        let ray = camera.get_ray(u, v, &mut rng);
+                        pixel_color += ray.color(world.as_ref(), &background, MAX_DEPTH, &mut rng);
+                    }
+
+                    row.push(pixel_color);
+                }
+
+                tx.send((j, row)).unwrap();
             }
+        });
+    }
+
+    drop(tx);
+
+    let mut rows: Vec<Option<Vec<Vector3<f32>>>> = vec![None; IMAGE_HEIGHT as usize];
+    let progress = ProgressBar::new(IMAGE_HEIGHT as u64);
+
+    for (j, row) in rx {
+        rows[j as usize] = Some(row);
+        progress.inc(1);
+    }
+
+    progress.finish();
+
+    let mut image = RgbImage::new(IMAGE_WIDTH as u32, IMAGE_HEIGHT as u32);
+
+    for (j, row) in rows.into_iter().enumerate() {
+        let image_y = (IMAGE_HEIGHT as usize - 1 - j) as u32;
 
-            content.push_str(&color_to_string(&pixel_color, SAMPLES_PER_PIXEL));
+        for (i, pixel_color) in row
+            .expect("every scanline was rendered by a worker")
+            .into_iter()
+            .enumerate()
+        {
+            image.put_pixel(i as u32, image_y, color_to_rgb(&pixel_color, SAMPLES_PER_PIXEL));
         }
     }
 
-    print!("{}", content);
-    eprintln!("\nDone!");
+    let output_path = std::env::args().nth(1).unwrap_or_else(|| "output.png".to_string());
+    image
+        .save(&output_path)
+        .unwrap_or_else(|err| panic!("failed to write {}: {}", output_path, err));
 }